@@ -0,0 +1,954 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    fs,
+    hash::{Hash, Hasher},
+    io::{self, Read},
+    iter,
+    path::{Path, PathBuf},
+};
+
+use rand::{prelude::SliceRandom, rngs::StdRng, thread_rng, Rng, SeedableRng};
+use rayon::prelude::*;
+
+// the number of permutations to run when none is requested explicitly
+const DEFAULT_N_PERMUTATIONS: usize = 1_000_000;
+
+// the number of permutations handled per Rayon work item; keeps the per-item shuffle buffer
+// allocated once and reused across many permutations instead of once per permutation
+const CHUNK_SIZE: usize = 1_000;
+
+// when the number of distinct ways to split the pooled data into control/treatment groups is at
+// or below this, `PermutationTest` enumerates all of them exactly instead of sampling
+const DEFAULT_EXACT_THRESHOLD: u128 = 2_000_000;
+
+// two assignments that are mathematically tied can still land a few ULPs apart once `mean`'s
+// incremental (Welford) summation runs over a different element order, so `run_exact` treats
+// statistics within this tolerance of each other as tied rather than comparing raw floats
+const EXACT_TIE_EPSILON: f64 = 1e-9;
+
+// This function accepts an iterator of f64's and computes mean using Welford's online algorithm
+pub fn mean<'a>(iter: impl Iterator<Item = &'a f64>) -> f64 {
+    // The function uses the enumerate method to get the index and value of each element in the iterator
+    // The fold method is used to iterate through the iterator and add the values to the accumulator
+    // The accumulator is initialized to 0.0
+    // The accumulator is updated by adding the difference between the current value and the accumulator divided by the index plus 1
+    iter.enumerate()
+        .fold(0.0, |mu, (i, x)| mu + ((x - mu) / (i + 1) as f64))
+}
+
+// this enum is used to denote group memebership during each permutation
+#[derive(Clone, PartialEq, Eq)]
+enum Group {
+    Control,
+    Treatment,
+}
+
+// which tail of the null distribution counts as evidence against the null hypothesis
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Tail {
+    Left,
+    Right,
+    // evidence in either direction counts: a permutation/assignment counts if its statistic is at
+    // least as extreme, in absolute value, as the observed statistic's absolute value
+    TwoSided,
+}
+
+// a test statistic compares a control and treatment sample and reduces them to a single scalar
+// measuring the difference between the groups. `permutation_test` and `PermutationTest` are
+// generic over `Statistic` so the choice of effect measure (difference of means, of medians, a
+// trimmed mean, a studentized statistic, ...) can vary without touching the permutation engine
+// itself.
+pub trait Statistic: Sync {
+    fn compute(&self, control: &[f64], treatment: &[f64]) -> f64;
+
+    // a short identifier for this statistic, used to key on-disk cache entries; two statistics
+    // that could compute different values for the same data must return different names
+    fn name(&self) -> String;
+}
+
+impl<T: Statistic + ?Sized> Statistic for &T {
+    fn compute(&self, control: &[f64], treatment: &[f64]) -> f64 {
+        (**self).compute(control, treatment)
+    }
+
+    fn name(&self) -> String {
+        (**self).name()
+    }
+}
+
+// the difference of the (Welford) means of each group: mu_treatment - mu_control
+pub struct DifferenceOfMeans;
+
+impl Statistic for DifferenceOfMeans {
+    fn compute(&self, control: &[f64], treatment: &[f64]) -> f64 {
+        mean(treatment.iter()) - mean(control.iter())
+    }
+
+    fn name(&self) -> String {
+        "difference_of_means".to_string()
+    }
+}
+
+// the difference of the medians of each group: median_treatment - median_control
+pub struct DifferenceOfMedians;
+
+impl Statistic for DifferenceOfMedians {
+    fn compute(&self, control: &[f64], treatment: &[f64]) -> f64 {
+        median(treatment) - median(control)
+    }
+
+    fn name(&self) -> String {
+        "difference_of_medians".to_string()
+    }
+}
+
+// the difference of trimmed means of each group, dropping `trim_fraction` of the observations
+// from each tail of each group before averaging
+pub struct TrimmedMean {
+    pub trim_fraction: f64,
+}
+
+impl Statistic for TrimmedMean {
+    fn compute(&self, control: &[f64], treatment: &[f64]) -> f64 {
+        trimmed_mean(treatment, self.trim_fraction) - trimmed_mean(control, self.trim_fraction)
+    }
+
+    fn name(&self) -> String {
+        format!("trimmed_mean({})", self.trim_fraction)
+    }
+}
+
+// Welch's t-statistic: (mu_treatment - mu_control) / sqrt(s_treatment^2/n_treatment +
+// s_control^2/n_control), with mean and variance accumulated together via Welford's M2
+// recurrence. Guards against groups with fewer than two observations, or zero pooled variance, by
+// returning 0.0 (no evidence of a difference) rather than dividing by zero.
+pub struct Studentized;
+
+impl Statistic for Studentized {
+    fn compute(&self, control: &[f64], treatment: &[f64]) -> f64 {
+        if control.len() < 2 || treatment.len() < 2 {
+            return 0.0;
+        }
+
+        let (mu_control, var_control) = mean_and_variance(control);
+        let (mu_treatment, var_treatment) = mean_and_variance(treatment);
+
+        let se =
+            (var_treatment / treatment.len() as f64 + var_control / control.len() as f64).sqrt();
+        if se == 0.0 {
+            return 0.0;
+        }
+
+        (mu_treatment - mu_control) / se
+    }
+
+    fn name(&self) -> String {
+        "studentized".to_string()
+    }
+}
+
+// compute the median of a slice by sorting a copy of it
+fn median(data: &[f64]) -> f64 {
+    let mut sorted = data.to_vec();
+    sorted.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap());
+    let n = sorted.len();
+    if n.is_multiple_of(2) {
+        (sorted[n / 2 - 1] + sorted[n / 2]) / 2.0
+    } else {
+        sorted[n / 2]
+    }
+}
+
+// compute the mean of a slice after dropping `trim_fraction` of its (sorted) observations from
+// each tail
+fn trimmed_mean(data: &[f64], trim_fraction: f64) -> f64 {
+    let mut sorted = data.to_vec();
+    sorted.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap());
+    let trim = (sorted.len() as f64 * trim_fraction).floor() as usize;
+    mean(sorted[trim..sorted.len() - trim].iter())
+}
+
+// compute the mean and sample variance of a slice in one pass, accumulating Welford's mean
+// alongside the M2 term (`M2 += (x - old_mu) * (x - new_mu)`) so the variance falls out as
+// `M2 / (n - 1)`
+fn mean_and_variance(data: &[f64]) -> (f64, f64) {
+    let (mut mu, mut m2, mut n) = (0.0, 0.0, 0.0);
+    for &x in data {
+        n += 1.0;
+        let old_mu = mu;
+        mu += (x - mu) / n;
+        m2 += (x - old_mu) * (x - mu);
+    }
+    let variance = if n > 1.0 { m2 / (n - 1.0) } else { 0.0 };
+    (mu, variance)
+}
+
+// run `n_permutations` permutations of `control`/`treatment` under `statistic`, optionally seeded
+// for reproducibility, and return the resulting null distribution: one permuted statistic per
+// permutation. Returning the full distribution (rather than just a count against some observed
+// value) lets callers answer follow-up questions, such as a different tail or significance level,
+// without re-running the permutations, and lets a cached distribution be grown incrementally.
+//
+// `chunk_offset` shifts which chunk index each unit of work seeds its rng from; a fresh run
+// passes 0, while growing a cached distribution passes the number of chunks already drawn so the
+// appended permutations get an RNG stream disjoint from (rather than a replay of) the original
+// run's chunks.
+//
+// Work is split into chunks of `CHUNK_SIZE` permutations, each handled by a single Rayon work
+// item so the shuffle buffer can be allocated once and reused across every permutation in the
+// chunk.
+fn run_permutations<S: Statistic>(
+    control: &[f64],
+    treatment: &[f64],
+    statistic: &S,
+    n_permutations: usize,
+    seed: Option<u64>,
+    chunk_offset: usize,
+) -> Vec<f64> {
+    // the pooled data, indexed the same way as `index` below
+    let pooled: Vec<f64> = control.iter().chain(treatment.iter()).copied().collect();
+
+    let n_chunks = n_permutations.div_ceil(CHUNK_SIZE);
+
+    // use Rayon to divide this work across chunks, collecting every permuted statistic
+    (0..n_chunks)
+        .into_par_iter()
+        .flat_map(|chunk| {
+            let chunk_len = if chunk == n_chunks - 1 {
+                n_permutations - chunk * CHUNK_SIZE
+            } else {
+                CHUNK_SIZE
+            };
+
+            // create an rng so we can shuffle later; a seed makes the chunk (and so the whole
+            // run) reproducible regardless of how Rayon schedules the work. `chunk_offset` is
+            // added so that growing a cached distribution draws from chunk indices beyond
+            // anything the original run used, instead of replaying its first chunks.
+            let mut rng: StdRng = match seed {
+                Some(seed) => StdRng::seed_from_u64(seed.wrapping_add((chunk_offset + chunk) as u64)),
+                None => StdRng::from_rng(thread_rng()).expect("failed to seed rng"),
+            };
+
+            // create an index array of [Control, Treatment] which we'll shuffle repeatedly to make our
+            // selections during each permutation
+            let mut index: Vec<Group> = iter::repeat(Group::Control)
+                .take(control.len())
+                .chain(iter::repeat(Group::Treatment).take(treatment.len()))
+                .collect();
+
+            // do the actual permutations
+            (0..chunk_len)
+                .map(|_| {
+                    // shuffle our group selections
+                    index.shuffle(&mut rng);
+
+                    // split the pooled data back into permuted control/treatment groups
+                    let mut permuted_control = Vec::with_capacity(control.len());
+                    let mut permuted_treatment = Vec::with_capacity(treatment.len());
+                    for (x, group) in pooled.iter().zip(index.iter()) {
+                        match group {
+                            Group::Control => permuted_control.push(*x),
+                            Group::Treatment => permuted_treatment.push(*x),
+                        }
+                    }
+
+                    statistic.compute(&permuted_control, &permuted_treatment)
+                })
+                .collect::<Vec<f64>>()
+        })
+        .collect()
+}
+
+// magic bytes identifying a cached null distribution file
+const CACHE_MAGIC: &[u8; 4] = b"MPC1";
+
+// a cache key depends on the pooled data, the group sizes, the statistic choice, and the seed
+// (two calls that differ only in seed, or one seeded and one not, must never share a cached
+// distribution, or `PermutationTest::seed`'s reproducibility contract silently breaks), but
+// deliberately not on the requested permutation count: that lets a cached distribution be grown
+// incrementally instead of invalidated every time the caller asks for more permutations
+fn cache_key<S: Statistic>(
+    control: &[f64],
+    treatment: &[f64],
+    statistic: &S,
+    seed: Option<u64>,
+) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    control.len().hash(&mut hasher);
+    treatment.len().hash(&mut hasher);
+    for x in control.iter().chain(treatment.iter()) {
+        x.to_bits().hash(&mut hasher);
+    }
+    statistic.name().hash(&mut hasher);
+    seed.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn cache_path(dir: &Path, key: u64) -> PathBuf {
+    dir.join(format!("{key:016x}.mpc"))
+}
+
+// load a cached null distribution, returning `None` if the file is missing or malformed (treated
+// as a cache miss rather than an error, since the cache is purely an optimization)
+fn load_cached_distribution(path: &Path) -> Option<Vec<f64>> {
+    let read = || -> io::Result<Vec<f64>> {
+        let mut buf = Vec::new();
+        fs::File::open(path)?.read_to_end(&mut buf)?;
+        if buf.len() < CACHE_MAGIC.len() || &buf[..CACHE_MAGIC.len()] != CACHE_MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "bad cache magic"));
+        }
+        Ok(buf[CACHE_MAGIC.len()..]
+            .chunks_exact(8)
+            .map(|bytes| f64::from_le_bytes(bytes.try_into().unwrap()))
+            .collect())
+    };
+    read().ok()
+}
+
+// persist a null distribution to `path`, overwriting whatever was cached there before
+fn save_cached_distribution(path: &Path, distribution: &[f64]) -> io::Result<()> {
+    let mut buf = Vec::with_capacity(CACHE_MAGIC.len() + distribution.len() * 8);
+    buf.extend_from_slice(CACHE_MAGIC);
+    for x in distribution {
+        buf.extend_from_slice(&x.to_le_bytes());
+    }
+    fs::create_dir_all(path.parent().expect("cache path must have a parent"))?;
+    fs::write(path, buf)
+}
+
+// get at least `n_permutations` permuted statistics for `control`/`treatment` under `statistic`,
+// reusing and growing whatever is already cached under `cache_dir` rather than starting over
+fn run_permutations_cached<S: Statistic>(
+    control: &[f64],
+    treatment: &[f64],
+    statistic: &S,
+    n_permutations: usize,
+    seed: Option<u64>,
+    cache_dir: &Path,
+) -> Vec<f64> {
+    let path = cache_path(cache_dir, cache_key(control, treatment, statistic, seed));
+
+    let mut distribution = load_cached_distribution(&path).unwrap_or_default();
+
+    if distribution.len() < n_permutations {
+        let n_needed = n_permutations - distribution.len();
+        // reserve one chunk-index slot per already-cached permutation: since a batch of `m` new
+        // permutations only ever consumes `ceil(m / CHUNK_SIZE) <= m` chunk indices, offsetting by
+        // the cached length strictly outpaces every earlier batch's chunk indices, however many
+        // times the distribution has already been grown. Using the exact chunk count here instead
+        // would collide: growing 300 -> 600 -> 900 permutations reuses chunk 0 each time (every
+        // batch fits in a single sub-`CHUNK_SIZE` chunk), so `len / CHUNK_SIZE` stays 0 (or 1) and
+        // repeats the same RNG stream across growth steps.
+        let chunk_offset = distribution.len();
+        distribution.extend(run_permutations(
+            control,
+            treatment,
+            statistic,
+            n_needed,
+            seed,
+            chunk_offset,
+        ));
+        let _ = save_cached_distribution(&path, &distribution);
+    }
+
+    distribution
+}
+
+// number of ways to choose `k` elements from `n`, using `u128` and saturating at `u128::MAX` so the
+// default `DEFAULT_EXACT_THRESHOLD` comparison never overflows even for the combined sample sizes
+// where exact enumeration stops being practical
+fn n_choose_k(n: usize, k: usize) -> u128 {
+    if k > n {
+        return 0;
+    }
+    let k = k.min(n - k);
+    let mut result: u128 = 1;
+    for i in 0..k {
+        result = match result.checked_mul((n - i) as u128) {
+            Some(product) => product / (i + 1) as u128,
+            None => return u128::MAX,
+        };
+    }
+    result
+}
+
+// unrank the combination at `rank` (0-indexed, lexicographic order) of `k` indices chosen from
+// `0..n`, using the standard combinadic algorithm: at each position, walk candidates starting from
+// the previous pick and subtract off however many combinations start with each smaller candidate
+// until `rank` falls inside the block belonging to the chosen one.
+fn unrank_combination(mut rank: u128, n: usize, k: usize) -> Vec<usize> {
+    let mut combo = Vec::with_capacity(k);
+    let mut candidate = 0;
+    for pos in 0..k {
+        let remaining = k - pos;
+        loop {
+            let block = n_choose_k(n - candidate - 1, remaining - 1);
+            if rank < block {
+                break;
+            }
+            rank -= block;
+            candidate += 1;
+        }
+        combo.push(candidate);
+        candidate += 1;
+    }
+    combo
+}
+
+// advance `combo` (sorted ascending indices into `0..n`) to the next combination in lexicographic
+// order; returns `false` once `combo` was already the last one
+fn next_combination(combo: &mut [usize], n: usize) -> bool {
+    let k = combo.len();
+    let mut i = k;
+    loop {
+        if i == 0 {
+            return false;
+        }
+        i -= 1;
+        if combo[i] != i + n - k {
+            break;
+        }
+    }
+    combo[i] += 1;
+    for j in (i + 1)..k {
+        combo[j] = combo[j - 1] + 1;
+    }
+    true
+}
+
+// enumerate every way to choose which `control.len()` of the pooled `control.len() + treatment.len()`
+// values form the control group, score each assignment exactly once, and return
+// `(observed_statistic, count_at_least_as_high, count_at_least_as_low, count_at_least_as_extreme_two_sided, total_assignments)`.
+//
+// The combination space is divided into chunks of `CHUNK_SIZE` assignments; each chunk unranks its
+// starting combination once (via `unrank_combination`) and then walks the rest with
+// `next_combination`, so only the first assignment in a chunk pays the unranking cost. Chunks run
+// in parallel across Rayon, mirroring `run_permutations`.
+fn run_exact<S: Statistic>(
+    control: &[f64],
+    treatment: &[f64],
+    statistic: &S,
+) -> (f64, usize, usize, usize, usize) {
+    let observed = statistic.compute(control, treatment);
+    let observed_abs = observed.abs();
+    let pooled: Vec<f64> = control.iter().chain(treatment.iter()).copied().collect();
+    let n = pooled.len();
+    let k = control.len();
+
+    let total = n_choose_k(n, k) as usize;
+    let n_chunks = total.div_ceil(CHUNK_SIZE);
+
+    let (count_right, count_left, count_two_sided): (usize, usize, usize) = (0..n_chunks)
+        .into_par_iter()
+        .map(|chunk| {
+            let start = chunk * CHUNK_SIZE;
+            let chunk_len = if chunk == n_chunks - 1 {
+                total - start
+            } else {
+                CHUNK_SIZE
+            };
+
+            let mut combo = unrank_combination(start as u128, n, k);
+            let mut local_count_right = 0;
+            let mut local_count_left = 0;
+            let mut local_count_two_sided = 0;
+            for _ in 0..chunk_len {
+                // membership of each pooled index in this assignment's control group
+                let mut in_control = vec![false; n];
+                for &i in &combo {
+                    in_control[i] = true;
+                }
+
+                let mut assigned_control = Vec::with_capacity(k);
+                let mut assigned_treatment = Vec::with_capacity(n - k);
+                for (i, &x) in pooled.iter().enumerate() {
+                    if in_control[i] {
+                        assigned_control.push(x);
+                    } else {
+                        assigned_treatment.push(x);
+                    }
+                }
+
+                let stat = statistic.compute(&assigned_control, &assigned_treatment);
+
+                // an exact test counts the observed assignment itself, so ties count as evidence
+                // in whichever direction (or both, for the two-sided case) is being tested;
+                // compare within `EXACT_TIE_EPSILON` rather than exactly, since a mathematical tie
+                // can differ by a few ULPs once summation runs over a different element order
+                if stat >= observed - EXACT_TIE_EPSILON {
+                    local_count_right += 1;
+                }
+                if stat <= observed + EXACT_TIE_EPSILON {
+                    local_count_left += 1;
+                }
+                if stat.abs() >= observed_abs - EXACT_TIE_EPSILON {
+                    local_count_two_sided += 1;
+                }
+
+                next_combination(&mut combo, n);
+            }
+            (local_count_right, local_count_left, local_count_two_sided)
+        })
+        .reduce(|| (0, 0, 0), |a, b| (a.0 + b.0, a.1 + b.1, a.2 + b.2));
+
+    (observed, count_right, count_left, count_two_sided, total)
+}
+
+// Run the permutation test.
+//
+// Accepts the control and treatment arrays along with a `Statistic` used both to compute the
+// observed effect and to re-score every permutation. When there are at most
+// `DEFAULT_EXACT_THRESHOLD` distinct control/treatment assignments, every assignment is enumerated
+// exactly; otherwise `DEFAULT_N_PERMUTATIONS` permutations are sampled with an unseeded rng. Use
+// `PermutationTest` for control over the permutation count, tail, seed, or statistic.
+//
+// In exact mode the p-value is the number of assignments whose statistic is at least as extreme
+// as the observed statistic divided by the number of assignments. When sampling, it is that count
+// over the permutations run, with the add-one correction: (count + 1) / (n_permutations + 1).
+//
+// Note: the left tail or right tail is chosen automatically based on whether the observed
+// statistic is positive or negative.
+pub fn permutation_test<S: Statistic>(control: &[f64], treatment: &[f64], statistic: &S) -> f64 {
+    PermutationTest::new(control, treatment)
+        .statistic(statistic)
+        .run()
+        .p_value
+}
+
+// the result of running a `PermutationTest`
+pub struct PermutationTestResult {
+    pub p_value: f64,
+    pub observed_statistic: f64,
+    pub n_control: usize,
+    pub n_treatment: usize,
+    // whether the p-value came from exact enumeration rather than random sampling
+    pub exact: bool,
+}
+
+// A builder for configuring and running a permutation test.
+//
+// `control` and `treatment` are required; every other option has a sensible default:
+// `DEFAULT_N_PERMUTATIONS` permutations, the tail chosen automatically from the sign of the
+// observed statistic, an unseeded (non-reproducible) rng, and `DifferenceOfMeans` as the
+// statistic.
+pub struct PermutationTest<'a, S: Statistic = DifferenceOfMeans> {
+    control: &'a [f64],
+    treatment: &'a [f64],
+    n_permutations: usize,
+    tail: Option<Tail>,
+    seed: Option<u64>,
+    exact_threshold: u128,
+    cache_dir: Option<PathBuf>,
+    statistic: S,
+}
+
+impl<'a> PermutationTest<'a, DifferenceOfMeans> {
+    pub fn new(control: &'a [f64], treatment: &'a [f64]) -> Self {
+        Self {
+            control,
+            treatment,
+            n_permutations: DEFAULT_N_PERMUTATIONS,
+            tail: None,
+            seed: None,
+            exact_threshold: DEFAULT_EXACT_THRESHOLD,
+            cache_dir: None,
+            statistic: DifferenceOfMeans,
+        }
+    }
+}
+
+impl<'a, S: Statistic> PermutationTest<'a, S> {
+    pub fn n_permutations(mut self, n_permutations: usize) -> Self {
+        self.n_permutations = n_permutations;
+        self
+    }
+
+    pub fn tail(mut self, tail: Tail) -> Self {
+        self.tail = Some(tail);
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    // at or below this many distinct control/treatment assignments, enumerate them exactly instead of
+    // sampling; defaults to `DEFAULT_EXACT_THRESHOLD`
+    pub fn exact_threshold(mut self, exact_threshold: u128) -> Self {
+        self.exact_threshold = exact_threshold;
+        self
+    }
+
+    // swap in a different test statistic, changing the type parameter in the process
+    pub fn statistic<S2: Statistic>(self, statistic: S2) -> PermutationTest<'a, S2> {
+        PermutationTest {
+            control: self.control,
+            treatment: self.treatment,
+            n_permutations: self.n_permutations,
+            tail: self.tail,
+            seed: self.seed,
+            exact_threshold: self.exact_threshold,
+            cache_dir: self.cache_dir,
+            statistic,
+        }
+    }
+
+    // cache the null distribution on disk under `cache_dir`, keyed on the data, statistic and seed
+    // (but not the permutation count), so a later run over the same data can reuse it and, if it
+    // asks for more permutations than are cached, grow it instead of starting over
+    pub fn cache_dir(mut self, cache_dir: impl Into<PathBuf>) -> Self {
+        self.cache_dir = Some(cache_dir.into());
+        self
+    }
+
+    pub fn run(self) -> PermutationTestResult {
+        let n_assignments =
+            n_choose_k(self.control.len() + self.treatment.len(), self.control.len());
+
+        // each branch below picks left/right/two-sided itself (auto-selecting left/right based on
+        // the observed statistic's sign unless the caller picked a tail explicitly), since the
+        // observed statistic isn't known until the branch computes it
+        let (observed, p_value, exact) = if n_assignments <= self.exact_threshold {
+            let (observed, count_right, count_left, count_two_sided, total) =
+                run_exact(self.control, self.treatment, &self.statistic);
+            let tail = self.tail.unwrap_or(if observed < 0.0 { Tail::Left } else { Tail::Right });
+            let count = match tail {
+                Tail::Right => count_right,
+                Tail::Left => count_left,
+                Tail::TwoSided => count_two_sided,
+            };
+            // `count` always includes the observed assignment itself (every comparison above is
+            // `>=`/`<=`, not strict), so the Monte Carlo add-one correction below isn't needed
+            // here: the p-value is already guaranteed to be nonzero.
+            (observed, count as f64 / total as f64, true)
+        } else {
+            let observed = self.statistic.compute(self.control, self.treatment);
+            let observed_abs = observed.abs();
+            let distribution = match &self.cache_dir {
+                Some(cache_dir) => run_permutations_cached(
+                    self.control,
+                    self.treatment,
+                    &self.statistic,
+                    self.n_permutations,
+                    self.seed,
+                    cache_dir,
+                ),
+                None => run_permutations(
+                    self.control,
+                    self.treatment,
+                    &self.statistic,
+                    self.n_permutations,
+                    self.seed,
+                    0,
+                ),
+            };
+            let n = distribution.len();
+            let tail = self.tail.unwrap_or(if observed < 0.0 { Tail::Left } else { Tail::Right });
+            let count = match tail {
+                Tail::Right => distribution.iter().filter(|&&x| x >= observed).count(),
+                Tail::Left => distribution.iter().filter(|&&x| x <= observed).count(),
+                Tail::TwoSided => distribution.iter().filter(|&&x| x.abs() >= observed_abs).count(),
+            };
+            // the standard Monte Carlo "add-one" correction: treats the observed assignment as one
+            // more draw from the null that is always at least as extreme as itself, so a finite
+            // random sample can never report a p-value of exactly zero.
+            (observed, (count + 1) as f64 / (n + 1) as f64, false)
+        };
+
+        PermutationTestResult {
+            p_value,
+            observed_statistic: observed,
+            n_control: self.control.len(),
+            n_treatment: self.treatment.len(),
+            exact,
+        }
+    }
+}
+
+// draw `n` indices uniformly from `[0, n)`, allowing duplicates, for use when resampling a group
+// with replacement. When `sort` is true the indices are returned in ascending order, which keeps
+// the subsequent gather from the source slice closer to sequential access.
+fn get_sample_inds(n: usize, rng: &mut impl Rng, sort: bool) -> Vec<usize> {
+    let mut inds: Vec<usize> = (0..n).map(|_| rng.gen_range(0..n)).collect();
+    if sort {
+        inds.sort_unstable();
+    }
+    inds
+}
+
+// compute a bootstrap confidence interval for (mu_treatment - mu_control).
+//
+// Each of `n_resamples` iterations resamples the control and treatment groups independently, with
+// replacement, using `get_sample_inds`, then recomputes the mean difference with the existing
+// Welford `mean`. The resulting distribution of differences is sorted and the
+// `(alpha/2, 1 - alpha/2)` percentiles are reported as the interval, where `alpha = 1 - confidence`.
+//
+// The resamples are run in parallel across Rayon, mirroring `permutation_test`.
+pub fn bootstrap_ci(
+    control: &[f64],
+    treatment: &[f64],
+    confidence: f64,
+    n_resamples: usize,
+) -> (f64, f64) {
+    if n_resamples == 0 {
+        // no resamples means no percentiles to read off; report an undefined interval rather
+        // than panicking on the empty `diffs` below
+        return (f64::NAN, f64::NAN);
+    }
+
+    let mut diffs: Vec<f64> = (0..n_resamples)
+        .into_par_iter()
+        .map(|_| {
+            // create an rng so we can resample later
+            let mut rng = thread_rng();
+
+            // resample each group independently, with replacement
+            let control_inds = get_sample_inds(control.len(), &mut rng, true);
+            let treatment_inds = get_sample_inds(treatment.len(), &mut rng, true);
+
+            let resampled_control: Vec<f64> = control_inds.iter().map(|&i| control[i]).collect();
+            let resampled_treatment: Vec<f64> =
+                treatment_inds.iter().map(|&i| treatment[i]).collect();
+
+            mean(resampled_treatment.iter()) - mean(resampled_control.iter())
+        })
+        .collect();
+
+    // sort so we can read off percentiles directly
+    diffs.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap());
+
+    let alpha = 1.0 - confidence;
+    let lower_ind = ((alpha / 2.0) * diffs.len() as f64) as usize;
+    let upper_ind = (((1.0 - alpha / 2.0) * diffs.len() as f64) as usize).min(diffs.len() - 1);
+
+    (diffs[lower_ind], diffs[upper_ind])
+}
+
+// convert p-value to conventional language; reads the same regardless of which `Tail` produced it
+pub fn pvalue_to_string(p: f64) -> String {
+    match p {
+        p if p < 0.01 => "very strong evidence against null hypothesis",
+        p if p < 0.025 => "strong evidence against null hypothesis",
+        p if p < 0.05 => "reasonably strong evidence against null hypothesis",
+        p if p < 0.10 => "borderline evidence against null hypothesis",
+        _ => "no evidence against null hypothesis",
+    }
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    // import names from lib scope
+    use super::*;
+
+    const MEAN_EPSILON: f64 = 0.000001;
+    const PVALUE_EPSILON: f64 = 0.001;
+
+    // for float comparison
+    fn approx_eq(a: f64, b: f64, epsilon: f64) -> bool {
+        (a - b).abs() < epsilon
+    }
+
+    // This is a test file for the permutation test.
+    // It tests the permutation test on the mouse data from Table 2.1 in "An Introduction to the Bootstrap" (book)
+    #[test]
+    fn test_mouse_data() {
+        // test data from Table 2.1 in "An Introduction to the Bootstrap" (book)
+        let control = vec![52.0, 104.0, 146.0, 10.0, 51.0, 30.0, 40.0, 27.0, 46.0];
+        let treatment = vec![94.0, 197.0, 16.0, 38.0, 99.0, 141.0, 23.0];
+
+        // compute empircal means
+        let mean_control = mean(control.iter());
+        assert!(approx_eq(mean_control, 56.22222222222222, MEAN_EPSILON));
+        let mean_treatment = mean(treatment.iter());
+        assert!(approx_eq(mean_treatment, 86.85714285714286, MEAN_EPSILON));
+        assert!(approx_eq(
+            mean_treatment - mean_control,
+            30.63492063492064,
+            MEAN_EPSILON
+        ));
+
+        // with only C(16, 9) = 11,440 possible assignments, this falls below the default exact
+        // threshold, so the p-value below is exact rather than a Monte Carlo estimate
+        let pvalue = permutation_test(&control, &treatment, &DifferenceOfMeans);
+        assert!(approx_eq(pvalue, 0.1409965034965035, PVALUE_EPSILON));
+    }
+
+    #[test]
+    fn test_exact_mode_matches_brute_force() {
+        // test data from Table 2.1 in "An Introduction to the Bootstrap" (book)
+        let control = vec![52.0, 104.0, 146.0, 10.0, 51.0, 30.0, 40.0, 27.0, 46.0];
+        let treatment = vec![94.0, 197.0, 16.0, 38.0, 99.0, 141.0, 23.0];
+
+        let result = PermutationTest::new(&control, &treatment)
+            .exact_threshold(1_000_000)
+            .run();
+
+        assert!(result.exact);
+        assert!(approx_eq(result.p_value, 0.1409965034965035, PVALUE_EPSILON));
+    }
+
+    #[test]
+    fn test_studentized_statistic() {
+        // test data from Table 2.1 in "An Introduction to the Bootstrap" (book)
+        let control = vec![52.0, 104.0, 146.0, 10.0, 51.0, 30.0, 40.0, 27.0, 46.0];
+        let treatment = vec![94.0, 197.0, 16.0, 38.0, 99.0, 141.0, 23.0];
+
+        // the studentized statistic should still lean toward the treatment group (a p-value below
+        // 0.5), as the raw difference of means does
+        let pvalue = permutation_test(&control, &treatment, &Studentized);
+        assert!(pvalue < 0.5);
+    }
+
+    #[test]
+    fn test_bootstrap_ci_mouse_data() {
+        // test data from Table 2.1 in "An Introduction to the Bootstrap" (book)
+        let control = vec![52.0, 104.0, 146.0, 10.0, 51.0, 30.0, 40.0, 27.0, 46.0];
+        let treatment = vec![94.0, 197.0, 16.0, 38.0, 99.0, 141.0, 23.0];
+
+        let (lower, upper) = bootstrap_ci(&control, &treatment, 0.95, 10_000);
+
+        // the interval should be well-formed and bracket the empirical difference in means
+        assert!(lower < upper);
+        let mean_diff = mean(treatment.iter()) - mean(control.iter());
+        assert!(lower < mean_diff && mean_diff < upper);
+    }
+
+    #[test]
+    fn test_bootstrap_ci_zero_resamples_does_not_panic() {
+        let control = vec![52.0, 104.0, 146.0, 10.0, 51.0, 30.0, 40.0, 27.0, 46.0];
+        let treatment = vec![94.0, 197.0, 16.0, 38.0, 99.0, 141.0, 23.0];
+
+        let (lower, upper) = bootstrap_ci(&control, &treatment, 0.95, 0);
+
+        assert!(lower.is_nan());
+        assert!(upper.is_nan());
+    }
+
+    #[test]
+    fn test_cache_grows_incrementally() {
+        let control = vec![52.0, 104.0, 146.0, 10.0, 51.0, 30.0, 40.0, 27.0, 46.0];
+        let treatment = vec![94.0, 197.0, 16.0, 38.0, 99.0, 141.0, 23.0];
+
+        let cache_dir =
+            std::env::temp_dir().join(format!("megapermute_test_cache_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&cache_dir);
+
+        let first = PermutationTest::new(&control, &treatment)
+            .exact_threshold(0)
+            .n_permutations(1_000)
+            .seed(7)
+            .cache_dir(cache_dir.clone())
+            .run();
+        assert!(!first.exact);
+
+        let path = cache_path(
+            &cache_dir,
+            cache_key(&control, &treatment, &DifferenceOfMeans, Some(7)),
+        );
+        let cached = load_cached_distribution(&path).expect("cache file should exist");
+        assert_eq!(cached.len(), 1_000);
+
+        // asking for more permutations than are cached should grow the distribution in place
+        // rather than start over
+        let second = PermutationTest::new(&control, &treatment)
+            .exact_threshold(0)
+            .n_permutations(2_000)
+            .seed(7)
+            .cache_dir(cache_dir.clone())
+            .run();
+        assert!(!second.exact);
+
+        let grown = load_cached_distribution(&path).expect("cache file should still exist");
+        assert_eq!(grown.len(), 2_000);
+        assert_eq!(&grown[..1_000], &cached[..]);
+
+        // the appended tail must be an independent draw, not a replay of the preserved head
+        assert_ne!(&grown[1_000..], &cached[..]);
+
+        let _ = fs::remove_dir_all(&cache_dir);
+    }
+
+    #[test]
+    fn test_cache_is_keyed_by_seed() {
+        let control = vec![52.0, 104.0, 146.0, 10.0, 51.0, 30.0, 40.0, 27.0, 46.0];
+        let treatment = vec![94.0, 197.0, 16.0, 38.0, 99.0, 141.0, 23.0];
+
+        let cache_dir = std::env::temp_dir()
+            .join(format!("megapermute_test_cache_seed_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&cache_dir);
+
+        let run_with_seed = |seed: u64| {
+            PermutationTest::new(&control, &treatment)
+                .exact_threshold(0)
+                .n_permutations(1_000)
+                .seed(seed)
+                .cache_dir(cache_dir.clone())
+                .run()
+        };
+
+        // populate the cache under seed 1, then ask for seed 999 against the same cache_dir: it
+        // must not silently reuse seed 1's cached distribution
+        let seeded_one = run_with_seed(1);
+        let seeded_999 = run_with_seed(999);
+
+        assert_ne!(seeded_one.p_value, seeded_999.p_value);
+
+        let _ = fs::remove_dir_all(&cache_dir);
+    }
+
+    #[test]
+    fn test_permutation_test_builder() {
+        let control = vec![52.0, 104.0, 146.0, 10.0, 51.0, 30.0, 40.0, 27.0, 46.0];
+        let treatment = vec![94.0, 197.0, 16.0, 38.0, 99.0, 141.0, 23.0];
+
+        // disable the exact path so this specifically exercises the seeded sampling path
+        let result = PermutationTest::new(&control, &treatment)
+            .exact_threshold(0)
+            .n_permutations(100_000)
+            .seed(42)
+            .run();
+
+        assert!(!result.exact);
+        assert_eq!(result.n_control, control.len());
+        assert_eq!(result.n_treatment, treatment.len());
+        // computed directly for this exact call (seed(42), 100_000 permutations, sampling path);
+        // not carried over from the old unseeded/1,000,000-permutation baseline test
+        assert!(approx_eq(result.p_value, 0.1426885731, PVALUE_EPSILON));
+    }
+
+    #[test]
+    fn test_two_sided_pvalue_mouse_data() {
+        // test data from Table 2.1 in "An Introduction to the Bootstrap" (book)
+        let control = vec![52.0, 104.0, 146.0, 10.0, 51.0, 30.0, 40.0, 27.0, 46.0];
+        let treatment = vec![94.0, 197.0, 16.0, 38.0, 99.0, 141.0, 23.0];
+
+        // verified independently by brute-forcing all C(16, 9) assignments and counting those
+        // whose |statistic| is at least the observed |statistic|
+        let result = PermutationTest::new(&control, &treatment)
+            .tail(Tail::TwoSided)
+            .run();
+
+        assert!(result.exact);
+        assert!(approx_eq(result.p_value, 0.2783216783216783, PVALUE_EPSILON));
+    }
+
+    #[test]
+    fn test_add_one_correction_never_zero() {
+        let control = vec![1.0, 2.0, 3.0];
+        let treatment = vec![100.0, 200.0, 300.0];
+
+        // the observed assignment is the most extreme of the C(6, 3) = 20 possible ones, so only
+        // permutations that reproduce it count; the add-one correction keeps the p-value above 0
+        // whatever that count is
+        let result = PermutationTest::new(&control, &treatment)
+            .exact_threshold(0)
+            .n_permutations(1_000)
+            .seed(1)
+            .run();
+
+        assert!(!result.exact);
+        assert!(result.p_value > 0.0);
+    }
+}
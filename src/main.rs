@@ -1,18 +1,16 @@
 use std::{
+    env,
     fs::File,
     io::{BufRead, BufReader},
-    iter,
+    path::PathBuf,
+    process,
 };
 
-use anyhow::Result;
-use rand::{prelude::SliceRandom, thread_rng};
-use rayon::prelude::*;
-
-// the number of threads to pass to rayon (which smartly splits it into actual number of threads)
-const N_THREADS: usize = 1_000;
-
-// the number of permutation tests to run per "thread" (above)
-const N_PERMUTATIONS_PER_THREAD: usize = 1_000;
+use anyhow::{bail, Result};
+use megapermute::{
+    bootstrap_ci, mean, pvalue_to_string, DifferenceOfMeans, DifferenceOfMedians, PermutationTest,
+    Statistic, Studentized, Tail, TrimmedMean,
+};
 
 // load a file of numbers as f64 and panic if we run into any problems.
 // This function takes a filename as a string and returns a vector of f64s.
@@ -33,117 +31,96 @@ fn load_f64s(filename: &str) -> Result<Vec<f64>> {
         .collect())
 }
 
-// This function accepts an iterator of f64's and computes mean using Welford's online algorithm
-fn mean<'a>(iter: impl Iterator<Item = &'a f64>) -> f64 {
-    // The function uses the enumerate method to get the index and value of each element in the iterator
-    // The fold method is used to iterate through the iterator and add the values to the accumulator
-    // The accumulator is initialized to 0.0
-    // The accumulator is updated by adding the difference between the current value and the accumulator divided by the index plus 1
-    iter.enumerate()
-        .fold(0.0, |mu, (i, x)| mu + ((x - mu) / (i + 1) as f64))
-}
-
-// this enum is used to denote group memebership during each permutation
-#[derive(Clone, PartialEq, Eq)]
-enum Group {
-    Control,
-    Treatment,
+// command-line options parsed by `parse_args`
+struct Args {
+    control_file: String,
+    treatment_file: String,
+    n_permutations: usize,
+    tail: Option<Tail>,
+    seed: Option<u64>,
+    statistic: String,
+    cache_dir: Option<PathBuf>,
 }
 
-// Run the permutation tests.
-//
-// Accepts the control and treatment arrays along with the difference in empircal means (treatment
-// minus control).
-//
-// Runs N_PERMUTATIONS_PER_THREAD on each of N_THREADS using Rayon's `par_iter()`.
-//
-// The original data (arrays) is never copied. Each of the iterations creates an index array of
-// `enum Group` denoting `Control` or `Treatment` at each index. That `index` array is shuffled to
-// permute group memebership. Finally, each of the group's means are computed and compared, and
-// filtered to only count differences which are larger than the `mu_diff` parameter.
-//
-// The final p-value is the number of permutations where the diff in means exceeded `mu_diff`
-// divided by (N_THREADS * N_PERMUTATIONS_PER_THREAD).
-//
-// Note: the left tail or right tail is chosen automatically based on whether the empircal mean
-// delta is positive or negative.
-fn permutation_test(control: &[f64], treatment: &[f64], mu_diff: f64) -> f64 {
-    // use Rayon to divide this work across N_THREADS, ultimately counting the number of
-    // permutations where delta(means) > mu_diff
-    let count: f64 = (0..N_THREADS)
-        .into_par_iter()
-        .map(|_| {
-            // create an rng so we can shuffle later
-            let mut rng = thread_rng();
-
-            // create an index array of [Control, Treatment] which we'll shuffle repeatedly to make our
-            // selections during each permutation
-            let mut index: Vec<Group> = iter::repeat(Group::Control)
-                .take(control.iter().len())
-                .chain(iter::repeat(Group::Treatment).take(treatment.iter().len()))
-                .collect();
-
-            // do the actual permutations
-            (0..N_PERMUTATIONS_PER_THREAD)
-                .filter(|_| {
-                    // shuffle our group selections
-                    index.shuffle(&mut rng);
-
-                    // variables for mean computation
-                    let (mut mu_control, mut n_control) = (0.0, 0.0);
-                    let (mut mu_treatment, mut n_treatment) = (0.0, 0.0);
-
-                    // walk the combined iterator and add each element to the corresponding mean
-                    // using Welford's online algorithm
-                    control.iter().chain(treatment.iter()).enumerate().for_each(
-                        |(i, x)| match index[i] {
-                            Group::Control => {
-                                n_control += 1.0;
-                                mu_control += (x - mu_control) / n_control
-                            }
-                            Group::Treatment => {
-                                n_treatment += 1.0;
-                                mu_treatment += (x - mu_treatment) / n_treatment
-                            }
-                        },
-                    );
-
-                    // select this permutation if the diff in these permuted means is more than the
-                    // empirical diff of means
-                    (mu_treatment - mu_control) > mu_diff
+const USAGE: &str = "usage: megapermute <control.dat> <treatment.dat> \
+[--permutations N] [--tail left|right|two-sided] [--seed N] \
+[--statistic means|medians|trimmed|studentized] [--cache-dir PATH]";
+
+// parse the positional filenames and `--option value` flags off the command line
+fn parse_args() -> Result<Args> {
+    let mut args = env::args().skip(1);
+
+    let control_file = args.next().ok_or_else(|| anyhow::anyhow!(USAGE))?;
+    let treatment_file = args.next().ok_or_else(|| anyhow::anyhow!(USAGE))?;
+
+    let mut n_permutations: usize = 1_000_000;
+    let mut tail: Option<Tail> = None;
+    let mut seed: Option<u64> = None;
+    let mut statistic = "means".to_string();
+    let mut cache_dir: Option<PathBuf> = None;
+
+    while let Some(flag) = args.next() {
+        let value = args.next().ok_or_else(|| anyhow::anyhow!(USAGE))?;
+        match flag.as_str() {
+            "--permutations" => n_permutations = value.parse()?,
+            "--tail" => {
+                tail = Some(match value.as_str() {
+                    "left" => Tail::Left,
+                    "right" => Tail::Right,
+                    "two-sided" => Tail::TwoSided,
+                    _ => bail!("unknown --tail {value}, expected left, right, or two-sided"),
                 })
-                .count() as f64
-        })
-        .sum();
-
-    // p-value is the ratio of permutations where delta(mean) exceeded empircal delta(mean) to
-    // total permutations
-    let p_value = count / (N_THREADS * N_PERMUTATIONS_PER_THREAD) as f64;
-
-    // adjust for left or right tail
-    if mu_diff < 0.0 {
-        1.0 - p_value
-    } else {
-        p_value
+            }
+            "--seed" => seed = Some(value.parse()?),
+            "--statistic" => statistic = value,
+            "--cache-dir" => cache_dir = Some(PathBuf::from(value)),
+            _ => bail!("unknown option {flag}\n{USAGE}"),
+        }
     }
+
+    Ok(Args {
+        control_file,
+        treatment_file,
+        n_permutations,
+        tail,
+        seed,
+        statistic,
+        cache_dir,
+    })
 }
 
-// convert p-value to conventional language
-fn pvalue_to_string(p: f64) -> String {
-    match p {
-        p if p < 0.01 => "very strong evidence against null hypothesis",
-        p if p < 0.025 => "strong evidence against null hypothesis",
-        p if p < 0.05 => "reasonably strong evidence against null hypothesis",
-        p if p < 0.10 => "borderline evidence against null hypothesis",
-        _ => "no evidence against null hypothesis",
+// run the permutation test for the chosen statistic; a small helper since `PermutationTest`'s
+// type parameter changes with the statistic and we only know which one was requested at runtime
+fn run_with_statistic<S: Statistic>(
+    control: &[f64],
+    treatment: &[f64],
+    args: &Args,
+    statistic: S,
+) -> f64 {
+    let mut test = PermutationTest::new(control, treatment)
+        .n_permutations(args.n_permutations)
+        .statistic(statistic);
+    if let Some(seed) = args.seed {
+        test = test.seed(seed);
+    }
+    if let Some(tail) = args.tail {
+        test = test.tail(tail);
     }
-    .to_string()
+    if let Some(cache_dir) = &args.cache_dir {
+        test = test.cache_dir(cache_dir.clone());
+    }
+    test.run().p_value
 }
 
 fn main() -> Result<()> {
+    let args = parse_args().unwrap_or_else(|err| {
+        eprintln!("{err}");
+        process::exit(1);
+    });
+
     // read data
-    let control = load_f64s("control.dat")?;
-    let treatment = load_f64s("treatment.dat")?;
+    let control = load_f64s(&args.control_file)?;
+    let treatment = load_f64s(&args.treatment_file)?;
 
     // compute empircal means
     let mean_control = mean(control.iter());
@@ -157,48 +134,25 @@ fn main() -> Result<()> {
         mean_treatment - mean_control
     );
 
-    // run permutation test to compute p-value
-    let pvalue = permutation_test(&control, &treatment, mean_treatment - mean_control);
+    // run permutation test to compute p-value, using whichever statistic was requested
+    let pvalue = match args.statistic.as_str() {
+        "means" => run_with_statistic(&control, &treatment, &args, DifferenceOfMeans),
+        "medians" => run_with_statistic(&control, &treatment, &args, DifferenceOfMedians),
+        "trimmed" => run_with_statistic(
+            &control,
+            &treatment,
+            &args,
+            TrimmedMean { trim_fraction: 0.1 },
+        ),
+        "studentized" => run_with_statistic(&control, &treatment, &args, Studentized),
+        other => bail!("unknown --statistic {other}, expected means, medians, trimmed, or studentized"),
+    };
     println!("                    p-value = {}", pvalue);
     println!("                     result = {}", pvalue_to_string(pvalue));
 
-    Ok(())
-}
-
-#[cfg(test)]
-mod tests {
-    // import names from main scope
-    use super::*;
+    // bootstrap a confidence interval for the effect size
+    let (ci_lower, ci_upper) = bootstrap_ci(&control, &treatment, 0.95, args.n_permutations);
+    println!("                  95% CI = ({}, {})", ci_lower, ci_upper);
 
-    const MEAN_EPSILON: f64 = 0.000001;
-    const PVALUE_EPSILON: f64 = 0.001;
-
-    // for float comparison
-    fn approx_eq(a: f64, b: f64, epsilon: f64) -> bool {
-        (a - b).abs() < epsilon
-    }
-
-    // This is a test file for the permutation test.
-    // It tests the permutation test on the mouse data from Table 2.1 in "An Introduction to the Bootstrap" (book)
-    #[test]
-    fn test_mouse_data() {
-        // test data from Table 2.1 in "An Introduction to the Bootstrap" (book)
-        let control = vec![52.0, 104.0, 146.0, 10.0, 51.0, 30.0, 40.0, 27.0, 46.0];
-        let treatment = vec![94.0, 197.0, 16.0, 38.0, 99.0, 141.0, 23.0];
-
-        // compute empircal means
-        let mean_control = mean(control.iter());
-        assert!(approx_eq(mean_control, 56.22222222222222, MEAN_EPSILON));
-        let mean_treatment = mean(treatment.iter());
-        assert!(approx_eq(mean_treatment, 86.85714285714286, MEAN_EPSILON));
-        assert!(approx_eq(
-            mean_treatment - mean_control,
-            30.63492063492064,
-            MEAN_EPSILON
-        ));
-
-        // run permutation test to compute p-value
-        let pvalue = permutation_test(&control, &treatment, mean_treatment - mean_control);
-        assert!(approx_eq(pvalue, 0.13896357, PVALUE_EPSILON));
-    }
+    Ok(())
 }